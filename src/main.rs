@@ -1,11 +1,22 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use backoff::{backoff::Backoff, Error as BackoffError, ExponentialBackoff};
+use futures::stream::{SplitSink, SplitStream};
 use futures::{SinkExt, StreamExt};
+use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use streamunordered::{StreamUnordered, StreamYield};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
+use tokio_tungstenite::tungstenite::{http::StatusCode, Error as WsError};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 use tracing::{error, info, warn};
 
+/// A live WebSocket connection to the exchange.
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarketData {
     pub symbol: String,
@@ -14,81 +25,660 @@ pub struct MarketData {
     pub timestamp: u64,
 }
 
+/// A single exchange message. Real feeds multiplex several event kinds
+/// (ticker snapshots, individual trades, top-of-book quotes, heartbeats and
+/// subscription acks) on the same socket, tagged by a `type` field.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum FeedEvent {
+    #[serde(rename = "ticker")]
+    Ticker(Ticker),
+    #[serde(rename = "trade")]
+    Trade(Trade),
+    #[serde(rename = "quote")]
+    Quote(Quote),
+    #[serde(rename = "heartbeat")]
+    Heartbeat,
+    #[serde(rename = "subscribed")]
+    Subscribed { channels: Vec<String> },
+    #[serde(rename = "depth_snapshot")]
+    DepthSnapshot(DepthSnapshot),
+    #[serde(rename = "depth_update")]
+    DepthUpdate(DepthUpdate),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ticker {
+    /// Empty when the feed doesn't repeat the symbol on every frame (e.g. a
+    /// per-symbol stream); callers should fall back to the stream's own
+    /// subscription symbol in that case.
+    #[serde(rename = "sym", default)]
+    pub symbol: String,
+    #[serde(rename = "p")]
+    pub price: f64,
+    #[serde(rename = "v")]
+    pub volume: f64,
+    /// Epoch milliseconds.
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Trade {
+    /// Empty when the feed doesn't repeat the symbol on every frame (e.g. a
+    /// per-symbol stream); callers should fall back to the stream's own
+    /// subscription symbol in that case.
+    #[serde(rename = "sym", default)]
+    pub symbol: String,
+    #[serde(rename = "p")]
+    pub price: f64,
+    #[serde(rename = "s")]
+    pub size: f64,
+    /// Epoch milliseconds.
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Quote {
+    /// Empty when the feed doesn't repeat the symbol on every frame (e.g. a
+    /// per-symbol stream); callers should fall back to the stream's own
+    /// subscription symbol in that case.
+    #[serde(rename = "sym", default)]
+    pub symbol: String,
+    #[serde(rename = "bp")]
+    pub bid_price: f64,
+    #[serde(rename = "ap")]
+    pub ask_price: f64,
+    /// Epoch milliseconds.
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+}
+
+/// A single price/quantity level in a depth snapshot or update frame.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthLevel {
+    #[serde(rename = "p")]
+    pub price: f64,
+    /// A quantity of `0` means the level should be removed from the book.
+    #[serde(rename = "q")]
+    pub quantity: f64,
+}
+
+/// A full order-book snapshot used to seed (or reseed) a symbol's book
+/// before incremental `DepthUpdate` diffs are applied.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthSnapshot {
+    /// Empty when the feed doesn't repeat the symbol on every frame (e.g. a
+    /// per-symbol stream); callers should fall back to the stream's own
+    /// subscription symbol in that case.
+    #[serde(rename = "sym", default)]
+    pub symbol: String,
+    #[serde(rename = "seq")]
+    pub sequence: u64,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+}
+
+/// An incremental depth diff. `sequence` must follow directly from the
+/// previous snapshot/update for the same symbol, or the book is stale.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthUpdate {
+    /// Empty when the feed doesn't repeat the symbol on every frame (e.g. a
+    /// per-symbol stream); callers should fall back to the stream's own
+    /// subscription symbol in that case.
+    #[serde(rename = "sym", default)]
+    pub symbol: String,
+    #[serde(rename = "seq")]
+    pub sequence: u64,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+}
+
+/// A price level as returned from `get_best_bid_ask`/`get_depth`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceLevel {
+    pub price: f64,
+    pub quantity: f64,
+}
+
+/// A live, per-symbol order book built from a snapshot plus incremental
+/// diffs. Tracks the exchange's update sequence number so a gap or
+/// out-of-order diff is detected instead of silently corrupting the book.
+#[derive(Debug, Clone, Default)]
+struct OrderBook {
+    bids: BTreeMap<OrderedFloat<f64>, f64>,
+    asks: BTreeMap<OrderedFloat<f64>, f64>,
+    sequence: u64,
+    /// Whether a full snapshot has ever been applied. An incremental diff
+    /// arriving before the first snapshot has no base to diff against, so
+    /// it's rejected rather than silently building a partial book.
+    seeded: bool,
+}
+
+impl OrderBook {
+    fn apply_snapshot(&mut self, bids: Vec<DepthLevel>, asks: Vec<DepthLevel>, sequence: u64) {
+        self.bids.clear();
+        self.asks.clear();
+        apply_levels(&mut self.bids, bids);
+        apply_levels(&mut self.asks, asks);
+        self.sequence = sequence;
+        self.seeded = true;
+    }
+
+    /// Applies an incremental diff. Returns `false` if the book hasn't been
+    /// seeded by a snapshot yet, or if `sequence` doesn't directly follow
+    /// the book's current sequence — either way the book is stale and must
+    /// be discarded pending a fresh snapshot.
+    fn apply_update(
+        &mut self,
+        bids: Vec<DepthLevel>,
+        asks: Vec<DepthLevel>,
+        sequence: u64,
+    ) -> bool {
+        if !self.seeded || sequence != self.sequence + 1 {
+            return false;
+        }
+
+        apply_levels(&mut self.bids, bids);
+        apply_levels(&mut self.asks, asks);
+        self.sequence = sequence;
+        true
+    }
+
+    fn best_bid_ask(&self) -> (Option<PriceLevel>, Option<PriceLevel>) {
+        let best_bid = self.bids.iter().next_back().map(|(p, q)| PriceLevel {
+            price: p.into_inner(),
+            quantity: *q,
+        });
+        let best_ask = self.asks.iter().next().map(|(p, q)| PriceLevel {
+            price: p.into_inner(),
+            quantity: *q,
+        });
+        (best_bid, best_ask)
+    }
+
+    fn depth(&self, n: usize) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        let bids = self
+            .bids
+            .iter()
+            .rev()
+            .take(n)
+            .map(|(p, q)| PriceLevel {
+                price: p.into_inner(),
+                quantity: *q,
+            })
+            .collect();
+        let asks = self
+            .asks
+            .iter()
+            .take(n)
+            .map(|(p, q)| PriceLevel {
+                price: p.into_inner(),
+                quantity: *q,
+            })
+            .collect();
+        (bids, asks)
+    }
+}
+
+fn apply_levels(book: &mut BTreeMap<OrderedFloat<f64>, f64>, levels: Vec<DepthLevel>) {
+    for level in levels {
+        let key = OrderedFloat(level.price);
+        if level.quantity <= 0.0 {
+            book.remove(&key);
+        } else {
+            book.insert(key, level.quantity);
+        }
+    }
+}
+
+/// Falls back to the originating stream's subscribed symbol when a payload
+/// leaves its own `sym` field empty.
+fn resolve_symbol(payload_symbol: String, stream_symbol: Option<&str>) -> String {
+    if payload_symbol.is_empty() {
+        stream_symbol.map(str::to_string).unwrap_or_default()
+    } else {
+        payload_symbol
+    }
+}
+
+/// A runtime subscription change, sent to a connected `FeedHandler` through
+/// its `FeedController`.
+#[derive(Debug, Clone)]
+pub enum FeedCommand {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+}
+
+/// A cloneable handle for changing a running feed's subscriptions without
+/// tearing down the connection.
+#[derive(Debug, Clone)]
+pub struct FeedController {
+    tx: mpsc::Sender<FeedCommand>,
+}
+
+impl FeedController {
+    pub async fn subscribe(&self, channels: Vec<String>) -> Result<()> {
+        self.tx
+            .send(FeedCommand::Subscribe(channels))
+            .await
+            .map_err(|e| anyhow!("feed handler is no longer listening: {}", e))
+    }
+
+    pub async fn unsubscribe(&self, channels: Vec<String>) -> Result<()> {
+        self.tx
+            .send(FeedCommand::Unsubscribe(channels))
+            .await
+            .map_err(|e| anyhow!("feed handler is no longer listening: {}", e))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FeedHandler {
     url: String,
     data: Arc<RwLock<Vec<MarketData>>>,
+    tick_tx: watch::Sender<Option<MarketData>>,
+    command_tx: mpsc::Sender<FeedCommand>,
+    command_rx: Arc<Mutex<mpsc::Receiver<FeedCommand>>>,
+    next_request_id: Arc<AtomicU64>,
+    order_books: Arc<RwLock<HashMap<String, OrderBook>>>,
+}
+
+/// What a `connect_many` stream was opened for: the URL to reconnect to and
+/// the symbol it carries, so frames from that stream can be attributed to
+/// the right symbol even if the payload itself doesn't repeat it.
+#[derive(Debug, Clone)]
+struct SubscriptionDescriptor {
+    url: String,
+    symbol: String,
+}
+
+/// The write half and descriptor for one of `connect_many`'s streams,
+/// indexed by its `StreamUnordered` token.
+struct StreamHandle {
+    write: SplitSink<WsStream, Message>,
+    descriptor: SubscriptionDescriptor,
 }
 
 impl FeedHandler {
     pub fn new(url: String) -> Self {
+        let (tick_tx, _) = watch::channel(None);
+        let (command_tx, command_rx) = mpsc::channel(32);
         Self {
             url,
             data: Arc::new(RwLock::new(Vec::new())),
+            tick_tx,
+            command_tx,
+            command_rx: Arc::new(Mutex::new(command_rx)),
+            next_request_id: Arc::new(AtomicU64::new(1)),
+            order_books: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// Subscribes to push-based updates: the returned receiver is notified
+    /// as each new `MarketData` tick is parsed, without polling
+    /// `get_latest_data` or cloning the whole history buffer. It holds only
+    /// the most recent tick, so ticks that arrive between two reads are
+    /// overwritten and only the latest one is seen.
+    pub fn subscribe(&self) -> watch::Receiver<Option<MarketData>> {
+        self.tick_tx.subscribe()
+    }
+
+    /// Returns a cloneable handle that lets callers subscribe/unsubscribe
+    /// channels on this feed while `connect` is running, instead of only
+    /// getting the hard-coded initial subscription.
+    pub fn controller(&self) -> FeedController {
+        FeedController {
+            tx: self.command_tx.clone(),
+        }
+    }
+
+    /// Connects to the feed and keeps it alive, retrying with exponential
+    /// backoff on transient failures instead of exiting. Runs until a
+    /// permanent error occurs (e.g. a malformed URL); `data` survives
+    /// across reconnects since it lives on `self`. The backoff resets once
+    /// a connection succeeds, so a blip after hours of stable uptime
+    /// reconnects promptly instead of inheriting a stale, near-maximum
+    /// interval.
     pub async fn connect(&self) -> Result<()> {
-        info!("Connecting to WebSocket: {}", self.url);
+        let mut backoff = ExponentialBackoff {
+            max_elapsed_time: None,
+            ..Default::default()
+        };
 
-        let (ws_stream, _) = connect_async(&self.url).await?;
-        info!("WebSocket connected successfully");
+        loop {
+            match self.connect_once(&mut backoff).await {
+                Ok(()) => return Ok(()),
+                Err(err) => match classify_error(err) {
+                    BackoffError::Permanent(err) => return Err(err),
+                    BackoffError::Transient { err, .. } => {
+                        let Some(dur) = backoff.next_backoff() else {
+                            return Err(err);
+                        };
+                        warn!("Reconnecting in {:?} after error: {}", dur, err);
+                        tokio::time::sleep(dur).await;
+                    }
+                },
+            }
+        }
+    }
 
-        let (mut write, mut read) = ws_stream.split();
+    /// Performs a single connect/subscribe/read cycle. Returns `Err` on any
+    /// disconnect (including a clean close) so the caller can reconnect.
+    /// Also drives the command channel so `FeedController` calls take effect
+    /// immediately instead of waiting for the next reconnect. Resets
+    /// `backoff` once the connection is established so a healthy session
+    /// doesn't carry a stale retry interval into its next reconnect.
+    async fn connect_once(&self, backoff: &mut ExponentialBackoff) -> Result<()> {
+        let (mut write, mut read) = Self::open_stream(&self.url).await?;
+        backoff.reset();
+        let mut commands = self.command_rx.lock().await;
 
-        // Subscribe to market data
-        let subscribe_msg = serde_json::json!({
-            "type": "subscribe",
-            "channels": ["ticker", "trades"]
+        loop {
+            tokio::select! {
+                msg = read.next() => {
+                    match msg {
+                        Some(Ok(Message::Text(text))) => {
+                            match serde_json::from_str::<FeedEvent>(&text) {
+                                Ok(event) => self.handle_event(event, None).await,
+                                Err(e) => warn!("Dropping unparseable feed message: {} ({})", text, e),
+                            }
+                        }
+                        Some(Ok(Message::Ping(payload))) => {
+                            write.send(Message::Pong(payload)).await?;
+                        }
+                        Some(Ok(Message::Close(_))) => {
+                            warn!("WebSocket connection closed");
+                            return Err(anyhow!("WebSocket connection closed"));
+                        }
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Err(anyhow!("WebSocket stream ended")),
+                        _ => {}
+                    }
+                }
+                Some(cmd) = commands.recv() => {
+                    self.send_command(&mut write, cmd).await?;
+                }
+            }
+        }
+    }
+
+    /// Serializes a `FeedCommand` into the exchange's
+    /// `{"method":"SUBSCRIBE","params":[...],"id":n}` frame (method
+    /// `"UNSUBSCRIBE"` for an unsubscribe) and sends it,
+    /// tagging it with a monotonically increasing request id.
+    async fn send_command(
+        &self,
+        write: &mut SplitSink<WsStream, Message>,
+        cmd: FeedCommand,
+    ) -> Result<()> {
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let (method, params) = match cmd {
+            FeedCommand::Subscribe(channels) => ("SUBSCRIBE", channels),
+            FeedCommand::Unsubscribe(channels) => ("UNSUBSCRIBE", channels),
+        };
+
+        let frame = serde_json::json!({
+            "method": method,
+            "params": params,
+            "id": id,
         });
 
-        write
-            .send(Message::Text(subscribe_msg.to_string()))
-            .await?;
+        write.send(Message::Text(frame.to_string())).await?;
+        info!("Sent {} command (id={})", method, id);
 
-        info!("Subscribed to market data channels");
+        Ok(())
+    }
 
-        // Process incoming messages
-        while let Some(msg) = read.next().await {
-            match msg {
-                Ok(Message::Text(text)) => {
-                    if let Ok(market_data) = serde_json::from_str::<MarketData>(&text) {
-                        info!(
-                            "Received: {} @ ${:.2} (vol: {:.2})",
-                            market_data.symbol, market_data.price, market_data.volume
-                        );
+    /// Connects to many WebSocket streams (e.g. one per symbol) concurrently
+    /// and polls all of them via a single `StreamUnordered`. Unlike
+    /// `connect`, a single stream dying only reconnects that stream; it
+    /// never tears down the others. Each `(url, symbol)` pair's symbol
+    /// attributes frames from that stream's token, so a feed that doesn't
+    /// echo `sym` on every frame is still routed correctly.
+    pub async fn connect_many(&self, subscriptions: Vec<(String, String)>) -> Result<()> {
+        let mut streams: StreamUnordered<SplitStream<WsStream>> = StreamUnordered::new();
+        let mut handles: HashMap<usize, StreamHandle> = HashMap::new();
 
-                        let mut data = self.data.write().await;
-                        data.push(market_data);
+        for (url, symbol) in subscriptions {
+            let (write, read) = Self::open_stream(&url).await?;
+            let token = streams.insert(read);
+            handles.insert(
+                token,
+                StreamHandle {
+                    write,
+                    descriptor: SubscriptionDescriptor { url, symbol },
+                },
+            );
+        }
 
-                        // Keep only last 1000 records
-                        if data.len() > 1000 {
-                            data.remove(0);
+        while let Some((yld, token)) = streams.next().await {
+            match yld {
+                StreamYield::Item(Ok(Message::Text(text))) => {
+                    let stream_symbol = handles.get(&token).map(|h| h.descriptor.symbol.as_str());
+                    match serde_json::from_str::<FeedEvent>(&text) {
+                        Ok(event) => self.handle_event(event, stream_symbol).await,
+                        Err(e) => warn!("Dropping unparseable feed message: {} ({})", text, e),
+                    }
+                }
+                StreamYield::Item(Ok(Message::Ping(payload))) => {
+                    if let Some(handle) = handles.get_mut(&token) {
+                        if let Err(e) = handle.write.send(Message::Pong(payload)).await {
+                            error!("Failed to pong stream {}: {}", token, e);
                         }
                     }
                 }
-                Ok(Message::Ping(payload)) => {
-                    write.send(Message::Pong(payload)).await?;
+                StreamYield::Item(Ok(Message::Close(_))) => {
+                    warn!("Stream {} closed by peer, reconnecting", token);
+                    self.reconnect_stream(token, &mut streams, &mut handles)
+                        .await;
                 }
-                Ok(Message::Close(_)) => {
-                    warn!("WebSocket connection closed");
-                    break;
+                StreamYield::Item(Err(e)) => {
+                    error!("Stream {} error: {}, reconnecting", token, e);
+                    self.reconnect_stream(token, &mut streams, &mut handles)
+                        .await;
                 }
-                Err(e) => {
-                    error!("WebSocket error: {}", e);
-                    break;
+                StreamYield::Item(_) => {}
+                StreamYield::Finished(_) => {
+                    warn!("Stream {} finished, reconnecting", token);
+                    self.reconnect_stream(token, &mut streams, &mut handles)
+                        .await;
                 }
-                _ => {}
             }
         }
 
         Ok(())
     }
 
+    /// Drops the dead entry for `token` and reconnects its underlying URL,
+    /// re-inserting it under a fresh token so the other streams are
+    /// unaffected.
+    async fn reconnect_stream(
+        &self,
+        token: usize,
+        streams: &mut StreamUnordered<SplitStream<WsStream>>,
+        handles: &mut HashMap<usize, StreamHandle>,
+    ) {
+        std::pin::Pin::new(&mut *streams).remove(token);
+        let Some(old) = handles.remove(&token) else {
+            return;
+        };
+
+        match Self::open_stream(&old.descriptor.url).await {
+            Ok((write, read)) => {
+                let new_token = streams.insert(read);
+                handles.insert(
+                    new_token,
+                    StreamHandle {
+                        write,
+                        descriptor: old.descriptor,
+                    },
+                );
+            }
+            Err(e) => {
+                error!(
+                    "Failed to reconnect stream for {}: {}",
+                    old.descriptor.url, e
+                );
+            }
+        }
+    }
+
+    /// Opens a WebSocket connection and sends the subscribe frame, returning
+    /// the split sink/stream halves for the caller to drive.
+    async fn open_stream(
+        url: &str,
+    ) -> Result<(SplitSink<WsStream, Message>, SplitStream<WsStream>)> {
+        info!("Connecting to WebSocket: {}", url);
+
+        let (ws_stream, _) = connect_async(url).await?;
+        info!("WebSocket connected successfully");
+
+        let (mut write, read) = ws_stream.split();
+
+        // Subscribe to market data
+        let subscribe_msg = serde_json::json!({
+            "type": "subscribe",
+            "channels": ["ticker", "trades"]
+        });
+
+        write.send(Message::Text(subscribe_msg.to_string())).await?;
+
+        info!("Subscribed to market data channels on {}", url);
+
+        Ok((write, read))
+    }
+
+    /// Routes a decoded feed event into the store. Tickers and trades feed
+    /// the `MarketData` history and the push channel; quotes, heartbeats and
+    /// subscription acks are logged and otherwise ignored. `stream_symbol`
+    /// is the originating `connect_many` stream's subscribed symbol (if
+    /// any), used to attribute frames whose payload doesn't repeat `sym`.
+    async fn handle_event(&self, event: FeedEvent, stream_symbol: Option<&str>) {
+        match event {
+            FeedEvent::Ticker(t) => {
+                self.record(MarketData {
+                    symbol: resolve_symbol(t.symbol, stream_symbol),
+                    price: t.price,
+                    volume: t.volume,
+                    timestamp: t.timestamp,
+                })
+                .await;
+            }
+            FeedEvent::Trade(t) => {
+                self.record(MarketData {
+                    symbol: resolve_symbol(t.symbol, stream_symbol),
+                    price: t.price,
+                    volume: t.size,
+                    timestamp: t.timestamp,
+                })
+                .await;
+            }
+            FeedEvent::Quote(q) => {
+                let symbol = resolve_symbol(q.symbol, stream_symbol);
+                info!(
+                    "Quote: {} bid ${:.2} / ask ${:.2}",
+                    symbol, q.bid_price, q.ask_price
+                );
+            }
+            FeedEvent::Heartbeat => {
+                info!("Heartbeat received");
+            }
+            FeedEvent::Subscribed { channels } => {
+                info!("Subscribed to channels: {:?}", channels);
+            }
+            FeedEvent::DepthSnapshot(s) => {
+                let symbol = resolve_symbol(s.symbol, stream_symbol);
+                let sequence = s.sequence;
+                let mut books = self.order_books.write().await;
+                books
+                    .entry(symbol.clone())
+                    .or_default()
+                    .apply_snapshot(s.bids, s.asks, sequence);
+                info!("Seeded order book for {} (seq={})", symbol, sequence);
+            }
+            FeedEvent::DepthUpdate(u) => {
+                let symbol = resolve_symbol(u.symbol, stream_symbol);
+                let mut books = self.order_books.write().await;
+                let book = books.entry(symbol.clone()).or_default();
+                if !book.apply_update(u.bids, u.asks, u.sequence) {
+                    warn!(
+                        "Order book gap for {}: got seq {} after {}, resyncing",
+                        symbol, u.sequence, book.sequence
+                    );
+                    books.remove(&symbol);
+                }
+            }
+        }
+    }
+
+    async fn record(&self, market_data: MarketData) {
+        info!(
+            "Received: {} @ ${:.2} (vol: {:.2})",
+            market_data.symbol, market_data.price, market_data.volume
+        );
+
+        let mut data = self.data.write().await;
+        data.push(market_data.clone());
+
+        // Keep only last 1000 records
+        if data.len() > 1000 {
+            data.remove(0);
+        }
+        drop(data);
+
+        let _ = self.tick_tx.send(Some(market_data));
+    }
+
     pub async fn get_latest_data(&self) -> Vec<MarketData> {
         let data = self.data.read().await;
         data.clone()
     }
+
+    /// Returns the current top-of-book bid and ask for `symbol`, or `None`
+    /// for either side that has no resting liquidity (or no book at all).
+    pub async fn get_best_bid_ask(&self, symbol: &str) -> (Option<PriceLevel>, Option<PriceLevel>) {
+        let books = self.order_books.read().await;
+        books
+            .get(symbol)
+            .map(OrderBook::best_bid_ask)
+            .unwrap_or((None, None))
+    }
+
+    /// Returns up to `n` aggregated bid/ask levels for `symbol`, best price
+    /// first on each side.
+    pub async fn get_depth(&self, symbol: &str, n: usize) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        let books = self.order_books.read().await;
+        books
+            .get(symbol)
+            .map(|book| book.depth(n))
+            .unwrap_or_default()
+    }
+}
+
+/// Classifies a connection failure for the retry supervisor: transport-level
+/// hiccups (resets, closes, IO errors) are transient and should be retried,
+/// while a malformed endpoint configuration is permanent and should abort.
+/// A handshake that came back with a `5xx` or `429` is also transient —
+/// only a genuinely bad URL/request or a non-retryable 4xx should stop
+/// reconnection for good.
+fn classify_error(err: anyhow::Error) -> BackoffError<anyhow::Error> {
+    let transient = match err.downcast_ref::<WsError>() {
+        Some(WsError::Url(_)) | Some(WsError::HttpFormat(_)) => false,
+        Some(WsError::Http(resp)) => {
+            let status = resp.status();
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        }
+        Some(_) => true,
+        None => true,
+    };
+
+    if transient {
+        BackoffError::transient(err)
+    } else {
+        BackoffError::permanent(err)
+    }
 }
 
 #[tokio::main]
@@ -158,4 +748,215 @@ mod tests {
         assert_eq!(stored_data.len(), 1);
         assert_eq!(stored_data[0].symbol, "BTCUSD");
     }
+
+    #[tokio::test]
+    async fn test_subscribe_receives_tick() {
+        let handler = FeedHandler::new("wss://test.com".to_string());
+        let mut rx = handler.subscribe();
+
+        let market_data = MarketData {
+            symbol: "ETHUSD".to_string(),
+            price: 3000.0,
+            volume: 2.0,
+            timestamp: 1234567890,
+        };
+
+        handler.tick_tx.send(Some(market_data.clone())).unwrap();
+        rx.changed().await.unwrap();
+
+        assert_eq!(rx.borrow().as_ref().unwrap().symbol, "ETHUSD");
+    }
+
+    #[test]
+    fn test_decode_ticker_event() {
+        let text = r#"{"type":"ticker","sym":"BTCUSD","p":50000.0,"v":1.5,"t":1234567890}"#;
+        let event: FeedEvent = serde_json::from_str(text).unwrap();
+        match event {
+            FeedEvent::Ticker(t) => {
+                assert_eq!(t.symbol, "BTCUSD");
+                assert_eq!(t.price, 50000.0);
+            }
+            other => panic!("expected Ticker, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_subscribed_event() {
+        let text = r#"{"type":"subscribed","channels":["ticker","trades"]}"#;
+        let event: FeedEvent = serde_json::from_str(text).unwrap();
+        match event {
+            FeedEvent::Subscribed { channels } => {
+                assert_eq!(channels, vec!["ticker".to_string(), "trades".to_string()]);
+            }
+            other => panic!("expected Subscribed, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_event_routes_trade_into_store() {
+        let handler = FeedHandler::new("wss://test.com".to_string());
+        handler
+            .handle_event(
+                FeedEvent::Trade(Trade {
+                    symbol: "BTCUSD".to_string(),
+                    price: 50000.0,
+                    size: 0.25,
+                    timestamp: 1234567890,
+                }),
+                None,
+            )
+            .await;
+
+        let stored_data = handler.get_latest_data().await;
+        assert_eq!(stored_data.len(), 1);
+        assert_eq!(stored_data[0].volume, 0.25);
+    }
+
+    #[tokio::test]
+    async fn test_controller_delivers_subscribe_command() {
+        let handler = FeedHandler::new("wss://test.com".to_string());
+        let controller = handler.controller();
+
+        controller
+            .subscribe(vec!["orderbook".to_string()])
+            .await
+            .unwrap();
+
+        let mut commands = handler.command_rx.lock().await;
+        match commands.recv().await.unwrap() {
+            FeedCommand::Subscribe(channels) => {
+                assert_eq!(channels, vec!["orderbook".to_string()]);
+            }
+            other => panic!("expected Subscribe, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_order_book_snapshot_then_update() {
+        let handler = FeedHandler::new("wss://test.com".to_string());
+
+        handler
+            .handle_event(
+                FeedEvent::DepthSnapshot(DepthSnapshot {
+                    symbol: "BTCUSD".to_string(),
+                    sequence: 1,
+                    bids: vec![DepthLevel {
+                        price: 100.0,
+                        quantity: 1.0,
+                    }],
+                    asks: vec![DepthLevel {
+                        price: 101.0,
+                        quantity: 2.0,
+                    }],
+                }),
+                None,
+            )
+            .await;
+
+        let (bid, ask) = handler.get_best_bid_ask("BTCUSD").await;
+        assert_eq!(bid.unwrap().price, 100.0);
+        assert_eq!(ask.unwrap().price, 101.0);
+
+        handler
+            .handle_event(
+                FeedEvent::DepthUpdate(DepthUpdate {
+                    symbol: "BTCUSD".to_string(),
+                    sequence: 2,
+                    bids: vec![DepthLevel {
+                        price: 100.0,
+                        quantity: 0.0,
+                    }],
+                    asks: vec![],
+                }),
+                None,
+            )
+            .await;
+
+        let (bid, _) = handler.get_best_bid_ask("BTCUSD").await;
+        assert!(bid.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_order_book_resyncs_on_sequence_gap() {
+        let handler = FeedHandler::new("wss://test.com".to_string());
+
+        handler
+            .handle_event(
+                FeedEvent::DepthSnapshot(DepthSnapshot {
+                    symbol: "BTCUSD".to_string(),
+                    sequence: 1,
+                    bids: vec![DepthLevel {
+                        price: 100.0,
+                        quantity: 1.0,
+                    }],
+                    asks: vec![],
+                }),
+                None,
+            )
+            .await;
+
+        // Sequence 3 skips 2, so the book should be dropped rather than
+        // silently applying a gapped diff.
+        handler
+            .handle_event(
+                FeedEvent::DepthUpdate(DepthUpdate {
+                    symbol: "BTCUSD".to_string(),
+                    sequence: 3,
+                    bids: vec![DepthLevel {
+                        price: 99.0,
+                        quantity: 1.0,
+                    }],
+                    asks: vec![],
+                }),
+                None,
+            )
+            .await;
+
+        let (bid, _) = handler.get_best_bid_ask("BTCUSD").await;
+        assert!(bid.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_event_attributes_symbolless_trade_to_stream() {
+        let handler = FeedHandler::new("wss://test.com".to_string());
+        handler
+            .handle_event(
+                FeedEvent::Trade(Trade {
+                    symbol: String::new(),
+                    price: 50000.0,
+                    size: 0.25,
+                    timestamp: 1234567890,
+                }),
+                Some("BTCUSD"),
+            )
+            .await;
+
+        let stored_data = handler.get_latest_data().await;
+        assert_eq!(stored_data.len(), 1);
+        assert_eq!(stored_data[0].symbol, "BTCUSD");
+    }
+
+    #[tokio::test]
+    async fn test_order_book_rejects_update_before_snapshot() {
+        let handler = FeedHandler::new("wss://test.com".to_string());
+
+        handler
+            .handle_event(
+                FeedEvent::DepthUpdate(DepthUpdate {
+                    symbol: "BTCUSD".to_string(),
+                    sequence: 1,
+                    bids: vec![DepthLevel {
+                        price: 100.0,
+                        quantity: 1.0,
+                    }],
+                    asks: vec![],
+                }),
+                None,
+            )
+            .await;
+
+        let (bid, ask) = handler.get_best_bid_ask("BTCUSD").await;
+        assert!(bid.is_none());
+        assert!(ask.is_none());
+    }
 }